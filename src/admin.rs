@@ -0,0 +1,168 @@
+//! `/admin` command router.
+//!
+//! `proc_admin_cmd` used to be a stub that always returned 401. This turns
+//! it into a small typed dispatcher: each command is a row in [`COMMANDS`]
+//! carrying the permission it requires and a handler taking the parsed
+//! `args` value, so a future admin UI can add commands without touching
+//! the request-parsing plumbing.
+
+use crate::auth::ApiAuth;
+use crate::stats;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct AdminRequest {
+    pub cmd: String,
+    #[serde(default)]
+    pub args: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminResponse {
+    pub code: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AdminResponse {
+    fn ok(data: Value) -> Self {
+        AdminResponse {
+            code: 0,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    pub(crate) fn err(code: i32, msg: impl Into<String>) -> Self {
+        AdminResponse {
+            code,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+/// Required clearance for a command; checked before its handler runs.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Permission {
+    Admin,
+}
+
+type Handler = fn(&Arc<stats::StatsMgr>, &Value) -> AdminResponse;
+
+struct Command {
+    name: &'static str,
+    permission: Permission,
+    handler: Handler,
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "list_hosts",
+        permission: Permission::Admin,
+        handler: list_hosts,
+    },
+    Command {
+        name: "reload_config",
+        permission: Permission::Admin,
+        handler: reload_config,
+    },
+    Command {
+        name: "mute_host",
+        permission: Permission::Admin,
+        handler: mute_host,
+    },
+    Command {
+        name: "unmute_host",
+        permission: Permission::Admin,
+        handler: unmute_host,
+    },
+    Command {
+        name: "force_offline",
+        permission: Permission::Admin,
+        handler: force_offline,
+    },
+];
+
+/// Looks up `req.cmd` in the command registry, checks the caller holds its
+/// required permission, then runs the handler. The caller is assumed to
+/// have already passed `ApiAuth` (see `proc_admin_cmd`); this only adds the
+/// extra admin-permission check per command.
+pub fn dispatch(
+    headers: &hyper::HeaderMap,
+    stats_mgr: &Arc<stats::StatsMgr>,
+    req: &AdminRequest,
+) -> AdminResponse {
+    let command = match COMMANDS.iter().find(|c| c.name == req.cmd) {
+        Some(command) => command,
+        None => return AdminResponse::err(404, format!("unknown command: {}", req.cmd)),
+    };
+
+    if command.permission == Permission::Admin && !caller_is_admin(headers) {
+        return AdminResponse::err(403, "admin permission required");
+    }
+
+    (command.handler)(stats_mgr, &req.args)
+}
+
+fn caller_is_admin(headers: &hyper::HeaderMap) -> bool {
+    match crate::auth::AnyAuth.check_auth(headers) {
+        Some(auth_id) => crate::G_CONFIG
+            .get()
+            .unwrap()
+            .read()
+            .unwrap()
+            .is_admin(&auth_id.user),
+        None => false,
+    }
+}
+
+fn list_hosts(stats_mgr: &Arc<stats::StatsMgr>, _args: &Value) -> AdminResponse {
+    match serde_json::from_str(&stats_mgr.get_stats_json()) {
+        Ok(hosts) => AdminResponse::ok(hosts),
+        Err(e) => AdminResponse::err(500, e.to_string()),
+    }
+}
+
+fn reload_config(stats_mgr: &Arc<stats::StatsMgr>, _args: &Value) -> AdminResponse {
+    let cfg = {
+        let current = crate::G_CONFIG.get().unwrap().read().unwrap();
+        crate::config::parse_config(current.config_path())
+    };
+    match stats_mgr.reload_config(cfg) {
+        Ok(_) => AdminResponse::ok(Value::Null),
+        Err(e) => AdminResponse::err(500, e.to_string()),
+    }
+}
+
+fn mute_host(stats_mgr: &Arc<stats::StatsMgr>, args: &Value) -> AdminResponse {
+    with_server_arg(args, |server| {
+        stats_mgr.mute(server);
+        AdminResponse::ok(Value::Null)
+    })
+}
+
+fn unmute_host(stats_mgr: &Arc<stats::StatsMgr>, args: &Value) -> AdminResponse {
+    with_server_arg(args, |server| {
+        stats_mgr.unmute(server);
+        AdminResponse::ok(Value::Null)
+    })
+}
+
+fn force_offline(stats_mgr: &Arc<stats::StatsMgr>, args: &Value) -> AdminResponse {
+    with_server_arg(args, |server| {
+        stats_mgr.force_offline(server);
+        AdminResponse::ok(Value::Null)
+    })
+}
+
+fn with_server_arg(args: &Value, f: impl FnOnce(&str) -> AdminResponse) -> AdminResponse {
+    match args.get("server").and_then(Value::as_str) {
+        Some(server) => f(server),
+        None => AdminResponse::err(400, "missing \"server\" argument"),
+    }
+}
@@ -0,0 +1,151 @@
+//! Pluggable request authentication.
+//!
+//! Protected endpoints used to check HTTP Basic credentials inline. This
+//! adds an `ApiAuth` trait so they can instead accept stateless bearer
+//! tickets minted by `POST /login`, letting the dashboard and `/ws`
+//! authenticate once instead of resending the password on every request.
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use http_auth_basic::Credentials;
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const TICKET_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Identity of a successfully authenticated caller.
+#[derive(Debug, Clone)]
+pub struct AuthId {
+    pub user: String,
+}
+
+/// Implemented by every supported authentication scheme so protected
+/// endpoints can accept whichever one the caller presents.
+pub trait ApiAuth {
+    fn check_auth(&self, headers: &hyper::HeaderMap) -> Option<AuthId>;
+}
+
+/// `Authorization: Basic <user:pass>`, checked against `G_CONFIG` the same
+/// way `stats_report` always has.
+pub struct BasicAuth;
+
+impl ApiAuth for BasicAuth {
+    fn check_auth(&self, headers: &hyper::HeaderMap) -> Option<AuthId> {
+        let header_value = headers.get(hyper::header::AUTHORIZATION)?.to_str().ok()?;
+        let credentials = Credentials::from_header(header_value.to_string()).ok()?;
+        if crate::G_CONFIG
+            .get()
+            .unwrap()
+            .read()
+            .unwrap()
+            .auth(&credentials.user_id, &credentials.password)
+        {
+            Some(AuthId {
+                user: credentials.user_id,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// `Authorization: Bearer <ticket>`, where `ticket` is an HMAC-SHA256
+/// signed `"{user}:{issued_unix_ts}"` minted by [`issue_ticket`]. Stateless:
+/// the server never stores a session, it just re-derives the MAC.
+pub struct TicketAuth;
+
+impl ApiAuth for TicketAuth {
+    fn check_auth(&self, headers: &hyper::HeaderMap) -> Option<AuthId> {
+        let header_value = headers.get(hyper::header::AUTHORIZATION)?.to_str().ok()?;
+        let ticket = header_value.strip_prefix("Bearer ")?;
+        verify_ticket(ticket)
+    }
+}
+
+/// Tries a bearer ticket first, falling back to Basic; this is what
+/// protected endpoints other than `/login` should use.
+pub struct AnyAuth;
+
+impl ApiAuth for AnyAuth {
+    fn check_auth(&self, headers: &hyper::HeaderMap) -> Option<AuthId> {
+        TicketAuth
+            .check_auth(headers)
+            .or_else(|| BasicAuth.check_auth(headers))
+    }
+}
+
+fn secret() -> String {
+    crate::G_CONFIG
+        .get()
+        .unwrap()
+        .read()
+        .unwrap()
+        .ticket_secret()
+        .to_string()
+}
+
+fn sign(payload: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret().as_bytes()).expect("HMAC accepts any key length");
+    mac.update(payload.as_bytes());
+    base64_url_encode(&mac.finalize().into_bytes())
+}
+
+/// Mints a bearer ticket for `user`, valid for `TICKET_TTL_SECS`.
+pub fn issue_ticket(user: &str) -> String {
+    let issued_ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let payload = format!("{}:{}", user, issued_ts);
+    let mac = sign(&payload);
+    base64_url_encode(format!("{}::{}", payload, mac).as_bytes())
+}
+
+/// Verifies a raw ticket string (as opposed to a `Bearer <ticket>` header
+/// value). Used where a ticket has to travel somewhere other than the
+/// `Authorization` header, e.g. a WebSocket upgrade's `?ticket=` query
+/// param, since browsers can't set custom headers on `new WebSocket()`.
+pub fn check_ticket(ticket: &str) -> Option<AuthId> {
+    verify_ticket(ticket)
+}
+
+fn verify_ticket(ticket: &str) -> Option<AuthId> {
+    let decoded = base64_url_decode(ticket)?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (payload, mac) = decoded.rsplit_once("::")?;
+    if !constant_time_eq(sign(payload).as_bytes(), mac.as_bytes()) {
+        return None;
+    }
+    let (user, issued_ts) = payload.split_once(':')?;
+    let issued_ts: u64 = issued_ts.parse().ok()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    if now.saturating_sub(issued_ts) > TICKET_TTL_SECS {
+        return None;
+    }
+    Some(AuthId {
+        user: user.to_string(),
+    })
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn base64_url_encode(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn base64_url_decode(s: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(s)
+        .ok()
+}
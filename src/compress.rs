@@ -0,0 +1,92 @@
+//! Transparent gzip/deflate content-encoding negotiation for response
+//! bodies, so large responses (the stats JSON, static assets) don't always
+//! go over the wire uncompressed.
+
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use hyper::{header, HeaderMap};
+use std::io::Write;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+    Identity,
+}
+
+/// Picks the best encoding the client's `Accept-Encoding` header offers,
+/// preferring gzip over deflate, falling back to identity. Honors `q=0`
+/// (and any non-positive weight) as an explicit refusal of that encoding,
+/// per RFC 7231 §5.3.1.
+pub fn negotiate(headers: &HeaderMap) -> ContentEncoding {
+    let accept = match headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(v) => v,
+        None => return ContentEncoding::Identity,
+    };
+
+    if accepts(accept, "gzip") {
+        ContentEncoding::Gzip
+    } else if accepts(accept, "deflate") {
+        ContentEncoding::Deflate
+    } else {
+        ContentEncoding::Identity
+    }
+}
+
+fn accepts(accept_encoding: &str, name: &str) -> bool {
+    accept_encoding.split(',').any(|directive| {
+        let mut parts = directive.split(';');
+        if parts.next().unwrap_or("").trim() != name {
+            return false;
+        }
+        let q = parts
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        q > 0.0
+    })
+}
+
+/// Compresses `body` with `encoding` at the configured level, unless it's
+/// below `min_size` or `encoding` is identity, in which case `body` is
+/// returned untouched. Returns the bytes to send plus the `Content-Encoding`
+/// header value to set, if any.
+pub fn encode(body: Vec<u8>, encoding: ContentEncoding) -> (Vec<u8>, Option<&'static str>) {
+    let cfg = crate::G_CONFIG.get().unwrap().read().unwrap();
+    if encoding == ContentEncoding::Identity || body.len() < cfg.compression_min_size() {
+        return (body, None);
+    }
+
+    let level = Compression::new(cfg.compression_level());
+    let compressed = match encoding {
+        ContentEncoding::Gzip => {
+            let mut enc = GzEncoder::new(Vec::new(), level);
+            enc.write_all(&body).ok().and_then(|_| enc.finish().ok())
+        }
+        ContentEncoding::Deflate => {
+            // HTTP's `Content-Encoding: deflate` is the zlib-wrapped format
+            // (RFC 1950), not raw DEFLATE (RFC 1951) — `ZlibEncoder` is the
+            // one that actually matches what `deflate` means on the wire.
+            let mut enc = ZlibEncoder::new(Vec::new(), level);
+            enc.write_all(&body).ok().and_then(|_| enc.finish().ok())
+        }
+        ContentEncoding::Identity => unreachable!(),
+    };
+
+    match compressed {
+        Some(compressed) => (
+            compressed,
+            Some(if encoding == ContentEncoding::Gzip {
+                "gzip"
+            } else {
+                "deflate"
+            }),
+        ),
+        // Compression failed for some reason; better to serve uncompressed
+        // than to fail the request.
+        None => (body, None),
+    }
+}
@@ -6,17 +6,24 @@ extern crate log;
 extern crate pretty_env_logger;
 use bytes::Buf;
 use clap::Parser;
-use http_auth_basic::Credentials;
 use once_cell::sync::OnceCell;
 use rust_embed::RustEmbed;
 use std::collections::HashMap;
 use std::io::Read;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
+mod admin;
+mod auth;
+mod compress;
 mod config;
 mod notifier;
 mod payload;
 mod stats;
+mod templates;
+mod tls;
+mod ws;
+
+use auth::ApiAuth;
 
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
@@ -31,34 +38,37 @@ static APP_VERSION: &'static str = concat!(
     ") - BUILD_TS:",
     env!("BUILD_ST")
 );
-static G_CONFIG: OnceCell<crate::config::Config> = OnceCell::new();
+// `RwLock`, not a bare `Config`, so the `reload_config` admin command can
+// swap in a reparsed config that every handler picks up on its next read.
+static G_CONFIG: OnceCell<RwLock<crate::config::Config>> = OnceCell::new();
 static NOTFOUND: &[u8] = b"Not Found";
 static UNAUTHORIZED: &[u8] = b"Unauthorized";
 
 #[derive(RustEmbed)]
 #[folder = "web"]
 #[prefix = "/"]
-struct Asset;
+pub(crate) struct Asset;
+
+/// Builds a `400` JSON error envelope (`{"code":1,"error":...}`), matching
+/// the `{"code":0,...}` shape success responses use, for request bodies
+/// that fail to parse instead of panicking the connection task.
+fn bad_request_json(err: impl std::fmt::Display) -> Result<Response<Body>> {
+    let mut resp = HashMap::new();
+    resp.insert(&"code", serde_json::Value::from(1 as i32));
+    resp.insert(&"error", serde_json::Value::from(err.to_string()));
+    let resp_str = serde_json::to_string(&resp)?;
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(resp_str))?)
+}
 
 async fn stats_report(
     req: Request<Body>,
     stats_mgr: &Arc<stats::StatsMgr>,
 ) -> Result<Response<Body>> {
     // auth
-    let mut auth_ok = false;
-    if let Some(auth) = req.headers().get(hyper::header::AUTHORIZATION) {
-        let auth_header_value = String::from(auth.to_str()?);
-        if let Ok(credentials) = Credentials::from_header(auth_header_value) {
-            if G_CONFIG
-                .get()
-                .unwrap()
-                .auth(&credentials.user_id, &credentials.password)
-            {
-                auth_ok = true;
-            }
-        }
-    }
-    if !auth_ok {
+    if auth::AnyAuth.check_auth(req.headers()).is_none() {
         return Ok(Response::builder()
             .status(StatusCode::UNAUTHORIZED)
             .body(UNAUTHORIZED.into())
@@ -69,10 +79,16 @@ async fn stats_report(
     let mut buffer = Vec::new();
     let whole_body = hyper::body::aggregate(req).await?;
     let json_size = whole_body.reader().read_to_end(&mut buffer)?;
-    let json_data = String::from_utf8(buffer).unwrap();
+
+    let json_data = match String::from_utf8(buffer) {
+        Ok(json_data) => json_data,
+        Err(e) => return bad_request_json(e),
+    };
 
     // report
-    stats_mgr.report(&json_data).unwrap();
+    if let Err(e) = stats_mgr.report(&json_data) {
+        return bad_request_json(e);
+    }
 
     let mut resp = HashMap::new();
     resp.insert(&"code", serde_json::Value::from(0 as i32));
@@ -86,24 +102,71 @@ async fn stats_report(
     Ok(response)
 }
 
-async fn get_stats_json(stats_mgr: &Arc<stats::StatsMgr>) -> Result<Response<Body>> {
-    let res = Response::builder()
+async fn login(req: Request<Body>) -> Result<Response<Body>> {
+    let auth_id = match auth::BasicAuth.check_auth(req.headers()) {
+        Some(auth_id) => auth_id,
+        None => {
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(UNAUTHORIZED.into())
+                .unwrap())
+        }
+    };
+
+    let ticket = auth::issue_ticket(&auth_id.user);
+    let mut resp = HashMap::new();
+    resp.insert(&"code", serde_json::Value::from(0 as i32));
+    resp.insert(&"ticket", serde_json::Value::from(ticket));
+    let resp_str = serde_json::to_string(&resp)?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/json")
-        .body(Body::from(stats_mgr.get_stats_json()))
-        .unwrap();
-    Ok(res)
+        .body(Body::from(resp_str))?)
+}
+
+async fn get_stats_json(
+    req: &Request<Body>,
+    stats_mgr: &Arc<stats::StatsMgr>,
+) -> Result<Response<Body>> {
+    let encoding = compress::negotiate(req.headers());
+    let (body, content_encoding) = compress::encode(stats_mgr.get_stats_json().into_bytes(), encoding);
+
+    let mut builder = Response::builder().header(header::CONTENT_TYPE, "application/json");
+    if let Some(content_encoding) = content_encoding {
+        builder = builder
+            .header(header::CONTENT_ENCODING, content_encoding)
+            .header(header::VARY, "Accept-Encoding");
+    }
+    Ok(builder.body(Body::from(body)).unwrap())
 }
 
-#[allow(unused)]
 async fn proc_admin_cmd(
     req: Request<Body>,
     stats_mgr: &Arc<stats::StatsMgr>,
 ) -> Result<Response<Body>> {
-    // TODO
-    return Ok(Response::builder()
-        .status(StatusCode::UNAUTHORIZED)
-        .body(UNAUTHORIZED.into())
-        .unwrap());
+    if auth::AnyAuth.check_auth(req.headers()).is_none() {
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(UNAUTHORIZED.into())
+            .unwrap());
+    }
+
+    let headers = req.headers().clone();
+    let mut buffer = Vec::new();
+    let whole_body = hyper::body::aggregate(req).await?;
+    whole_body.reader().read_to_end(&mut buffer)?;
+
+    let resp = match serde_json::from_slice::<admin::AdminRequest>(&buffer) {
+        Ok(admin_req) => admin::dispatch(&headers, stats_mgr, &admin_req),
+        Err(e) => admin::AdminResponse::err(400, e.to_string()),
+    };
+    let resp_str = serde_json::to_string(&resp)?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(resp_str))?)
 }
 
 async fn main_service_func(
@@ -113,14 +176,22 @@ async fn main_service_func(
     let req_path = req.uri().path();
     match (req.method(), req_path) {
         (&Method::POST, "/report") => stats_report(req, &stats_mgr).await,
-        (&Method::GET, "/json/stats.json") => get_stats_json(&stats_mgr).await,
+        (&Method::POST, "/login") => login(req).await,
+        (&Method::GET, "/json/stats.json") => get_stats_json(&req, &stats_mgr).await,
+        (&Method::GET, "/ws") => ws::handle_upgrade(req, stats_mgr).await,
         (&Method::POST, "/admin") => proc_admin_cmd(req, &stats_mgr).await,
         (&Method::GET, "/") | (&Method::GET, "/index.html") => {
-            let body = Body::from(Asset::get("/index.html").unwrap().data);
-            Ok(Response::builder()
-                .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-                .body(Body::from(body))
-                .unwrap())
+            let rendered = templates::render_index()?;
+            let encoding = compress::negotiate(req.headers());
+            let (body, content_encoding) = compress::encode(rendered.into_bytes(), encoding);
+            let mut builder =
+                Response::builder().header(header::CONTENT_TYPE, "text/html; charset=utf-8");
+            if let Some(content_encoding) = content_encoding {
+                builder = builder
+                    .header(header::CONTENT_ENCODING, content_encoding)
+                    .header(header::VARY, "Accept-Encoding");
+            }
+            Ok(builder.body(Body::from(body)).unwrap())
         }
         _ => {
             match req.method() {
@@ -131,11 +202,17 @@ async fn main_service_func(
                     {
                         if let Some(data) = Asset::get(&req_path) {
                             let ct = mime_guess::from_path(req_path);
-                            let resp = Response::builder()
-                                .header(header::CONTENT_TYPE, ct.first_raw().unwrap())
-                                .body(Body::from(data.data))
-                                .unwrap();
-                            return Ok(resp);
+                            let encoding = compress::negotiate(req.headers());
+                            let (body, content_encoding) =
+                                compress::encode(data.data.into_owned(), encoding);
+                            let mut builder = Response::builder()
+                                .header(header::CONTENT_TYPE, ct.first_raw().unwrap());
+                            if let Some(content_encoding) = content_encoding {
+                                builder = builder
+                                    .header(header::CONTENT_ENCODING, content_encoding)
+                                    .header(header::VARY, "Accept-Encoding");
+                            }
+                            return Ok(builder.body(Body::from(body)).unwrap());
                         } else {
                             error!("can't get => {:?}", req_path);
                         }
@@ -174,30 +251,64 @@ async fn main() -> Result<()> {
 
     let cfg = crate::config::parse_config(&args.config);
     debug!("{:?}", cfg);
-    G_CONFIG.set(cfg).unwrap();
-
-    let mut stats_mgr_ = stats::StatsMgr::new();
-    stats_mgr_.init(G_CONFIG.get().unwrap()).unwrap();
-    let stats_mgr = Arc::new(stats_mgr_);
-
-    let addr = G_CONFIG.get().unwrap().addr.parse().unwrap();
-
-    let http_service = make_service_fn(move |_| {
-        // Move a clone into the `service_fn`.
-        let stats_mgr = stats_mgr.clone();
-        async {
-            Ok::<_, GenericError>(service_fn(move |req| {
-                // Clone again to ensure that client outlives this closure.
-                main_service_func(req, stats_mgr.clone())
-            }))
+    G_CONFIG.set(RwLock::new(cfg)).unwrap();
+
+    templates::init()?;
+
+    let stats_mgr = Arc::new(stats::StatsMgr::new());
+    tokio::spawn(stats::run_refresh_loop(stats_mgr.clone()));
+
+    let addr = G_CONFIG.get().unwrap().read().unwrap().addr.parse().unwrap();
+    let tls_acceptor = match (
+        G_CONFIG.get().unwrap().read().unwrap().tls_cert(),
+        G_CONFIG.get().unwrap().read().unwrap().tls_key(),
+    ) {
+        (Some(cert), Some(key)) => Some(tls::load_acceptor(cert, key)?),
+        _ => None,
+    };
+
+    match tls_acceptor {
+        Some(acceptor) => {
+            let https_task = tokio::spawn(tls::serve_https(
+                addr,
+                acceptor,
+                stats_mgr.clone(),
+                shutdown_signal(),
+            ));
+
+            if let Some(http_addr) = G_CONFIG.get().unwrap().read().unwrap().http_addr() {
+                let http_addr = http_addr.parse()?;
+                let http_task = tokio::spawn(tls::serve_http_redirect(
+                    http_addr,
+                    addr.port(),
+                    shutdown_signal(),
+                ));
+                let (https_res, http_res) = tokio::join!(https_task, http_task);
+                https_res??;
+                http_res??;
+            } else {
+                https_task.await??;
+            }
         }
-    });
+        None => {
+            let http_service = make_service_fn(move |_| {
+                // Move a clone into the `service_fn`.
+                let stats_mgr = stats_mgr.clone();
+                async {
+                    Ok::<_, GenericError>(service_fn(move |req| {
+                        // Clone again to ensure that client outlives this closure.
+                        main_service_func(req, stats_mgr.clone())
+                    }))
+                }
+            });
 
-    println!("Listening on http://{}", addr);
-    let server = Server::bind(&addr).serve(http_service);
-    let graceful = server.with_graceful_shutdown(shutdown_signal());
-    if let Err(e) = graceful.await {
-        eprintln!("server error: {}", e);
+            println!("Listening on http://{}", addr);
+            let server = Server::bind(&addr).serve(http_service);
+            let graceful = server.with_graceful_shutdown(shutdown_signal());
+            if let Err(e) = graceful.await {
+                eprintln!("server error: {}", e);
+            }
+        }
     }
 
     Ok(())
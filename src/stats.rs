@@ -0,0 +1,181 @@
+//! Tracks the latest report from each monitored host and owns the
+//! broadcast channel that `/ws` subscribes to for live pushes.
+//!
+//! Every mutation of the host map — an incoming `/report`, an admin
+//! mute/unmute or force-offline, and the periodic sweep that flips a
+//! quiet host to offline — republishes the current snapshot so `/ws`
+//! clients never see stale state.
+
+use crate::config::Config;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+// Small enough that a lagging `/ws` client just misses a few snapshots
+// instead of making everyone else wait on it.
+const BROADCAST_CAPACITY: usize = 16;
+const OFFLINE_AFTER_SECS: u64 = 90;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+struct HostEntry {
+    report: serde_json::Value,
+    last_seen: u64,
+    online: bool,
+    muted: bool,
+}
+
+pub struct StatsMgr {
+    hosts: Mutex<HashMap<String, HostEntry>>,
+    updates: broadcast::Sender<String>,
+}
+
+impl StatsMgr {
+    pub fn new() -> Self {
+        let (updates, _) = broadcast::channel(BROADCAST_CAPACITY);
+        StatsMgr {
+            hosts: Mutex::new(HashMap::new()),
+            updates,
+        }
+    }
+
+    /// Subscribes to stats-map refreshes; used by `/ws` to forward pushes.
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.updates.subscribe()
+    }
+
+    pub fn report(&self, json_data: &str) -> crate::Result<()> {
+        let report: serde_json::Value = serde_json::from_str(json_data)?;
+        let name = report
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .ok_or("report missing \"name\"")?
+            .to_string();
+
+        let now = now_unix();
+        {
+            let mut hosts = self.hosts.lock().unwrap();
+            let entry = hosts.entry(name).or_insert_with(|| HostEntry {
+                report: serde_json::Value::Null,
+                last_seen: now,
+                online: true,
+                muted: false,
+            });
+            entry.report = report;
+            entry.last_seen = now;
+            entry.online = true;
+        }
+        self.publish_snapshot();
+        Ok(())
+    }
+
+    pub fn get_stats_json(&self) -> String {
+        self.snapshot_json()
+    }
+
+    /// Atomically swaps the reparsed config into `G_CONFIG`, the `RwLock`
+    /// every handler (auth, templates, compression, admin) already reads
+    /// through — so a `reload_config` admin command is actually observable
+    /// on the next request instead of updating state nothing looks at.
+    pub fn reload_config(&self, cfg: Config) -> crate::Result<()> {
+        *crate::G_CONFIG.get().unwrap().write().unwrap() = cfg;
+        Ok(())
+    }
+
+    /// Suppresses notifier alerts for `server` without hiding it from the
+    /// dashboard — an operator muting a host during maintenance still wants
+    /// to see it sitting there, just without paging anyone about it.
+    pub fn mute(&self, server: &str) {
+        self.set_muted(server, true);
+    }
+
+    pub fn unmute(&self, server: &str) {
+        self.set_muted(server, false);
+    }
+
+    pub fn force_offline(&self, server: &str) {
+        {
+            let mut hosts = self.hosts.lock().unwrap();
+            if let Some(host) = hosts.get_mut(server) {
+                host.online = false;
+            }
+        }
+        self.publish_snapshot();
+    }
+
+    fn set_muted(&self, server: &str, muted: bool) {
+        {
+            let mut hosts = self.hosts.lock().unwrap();
+            if let Some(host) = hosts.get_mut(server) {
+                host.muted = muted;
+            }
+        }
+        crate::notifier::set_muted(server, muted);
+        self.publish_snapshot();
+    }
+
+    fn snapshot_json(&self) -> String {
+        let hosts = self.hosts.lock().unwrap();
+        let snapshot: HashMap<&String, serde_json::Value> = hosts
+            .iter()
+            .map(|(name, host)| (name, stamp_live_flags(&host.report, host)))
+            .collect();
+        serde_json::to_string(&snapshot).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Republishes the current snapshot to every `/ws` subscriber. Called
+    /// after any mutation of the host map, not just an incoming report.
+    fn publish_snapshot(&self) {
+        let _ = self.updates.send(self.snapshot_json());
+    }
+
+    /// Flips any host that's gone quiet past `OFFLINE_AFTER_SECS` to
+    /// offline and returns whether anything changed, so `run_refresh_loop`
+    /// can republish and `/ws` clients don't keep showing a host as online
+    /// forever.
+    fn sweep_offline(&self) -> bool {
+        let mut hosts = self.hosts.lock().unwrap();
+        let now = now_unix();
+        let mut changed = false;
+        for host in hosts.values_mut() {
+            if host.online && now.saturating_sub(host.last_seen) > OFFLINE_AFTER_SECS {
+                host.online = false;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Stamps the live `online`/`muted` flags onto a host's last report, so a
+/// client reading the snapshot can tell a host gone quiet from one still
+/// reporting, and a muted host from an unmuted one, without the dashboard
+/// needing a separate request per host.
+fn stamp_live_flags(report: &serde_json::Value, host: &HostEntry) -> serde_json::Value {
+    let mut stamped = report.clone();
+    if let serde_json::Value::Object(fields) = &mut stamped {
+        fields.insert("online".to_string(), serde_json::Value::Bool(host.online));
+        fields.insert("muted".to_string(), serde_json::Value::Bool(host.muted));
+    }
+    stamped
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Background task: periodically sweeps for hosts that stopped reporting
+/// and republishes when anything changes. Spawned once in `main` after
+/// `StatsMgr` is wrapped in an `Arc`.
+pub async fn run_refresh_loop(stats_mgr: Arc<StatsMgr>) {
+    let mut tick = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        tick.tick().await;
+        if stats_mgr.sweep_offline() {
+            stats_mgr.publish_snapshot();
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Server-side Handlebars rendering for the embedded dashboard index.
+//!
+//! `index.html` used to be served byte-for-byte from `Asset`. This
+//! registers it (and any partials shipped alongside it) as Handlebars
+//! templates at startup and renders them per request with a context built
+//! from `G_CONFIG`, so operators can customize branding and layout purely
+//! through `config.toml` without rebuilding the binary.
+//!
+//! `index.html` is a full JS app, not a template written for us, so it's
+//! only registered as a Handlebars template if it opts in with
+//! [`TEMPLATE_MARKER`] somewhere in the source — a JS app that merely
+//! happens to parse as valid Handlebars (e.g. a literal `{{foo}}` in an
+//! inline script) would otherwise get silently rendered to an empty
+//! string instead of being left alone. Partials under `/partials/` are
+//! always registered; living in that directory is itself the opt-in.
+
+use handlebars::Handlebars;
+use once_cell::sync::OnceCell;
+use serde_json::json;
+
+static REGISTRY: OnceCell<Handlebars<'static>> = OnceCell::new();
+
+const INDEX_TEMPLATE: &str = "index";
+
+/// Marker `index.html` must contain to be treated as a Handlebars template
+/// rather than served as a static asset.
+const TEMPLATE_MARKER: &str = "<!-- serverstatus:template -->";
+
+/// Registers the embedded templates. Call once at startup, before the
+/// server starts accepting requests.
+pub fn init() -> crate::Result<()> {
+    let mut hb = Handlebars::new();
+    hb.set_strict_mode(false);
+
+    match crate::Asset::get("/index.html") {
+        Some(index_html) => match String::from_utf8(index_html.data.into_owned()) {
+            Ok(source) => {
+                if !source.contains(TEMPLATE_MARKER) {
+                    debug!("index.html has no {} marker; serving it unrendered", TEMPLATE_MARKER);
+                } else if let Err(e) = hb.register_template_string(INDEX_TEMPLATE, source) {
+                    warn!(
+                        "index.html doesn't parse as Handlebars ({}); serving it unrendered",
+                        e
+                    );
+                }
+            }
+            Err(e) => warn!("index.html isn't valid UTF-8 ({}); serving it unrendered", e),
+        },
+        None => warn!("no embedded index.html found"),
+    }
+
+    for partial in crate::Asset::iter().filter(|p| p.starts_with("/partials/")) {
+        let asset = match crate::Asset::get(&partial) {
+            Some(asset) => asset,
+            None => continue,
+        };
+        let name = partial
+            .trim_start_matches("/partials/")
+            .trim_end_matches(".hbs");
+        match String::from_utf8(asset.data.into_owned()) {
+            Ok(source) => {
+                if let Err(e) = hb.register_partial(name, source) {
+                    warn!("partial \"{}\" doesn't parse as Handlebars ({})", name, e);
+                }
+            }
+            Err(e) => warn!("partial \"{}\" isn't valid UTF-8 ({})", name, e),
+        }
+    }
+
+    REGISTRY
+        .set(hb)
+        .map_err(|_| "templates::init called more than once")?;
+    Ok(())
+}
+
+/// Renders the dashboard index with group/server display names, page
+/// title, theme, refresh interval, and visible columns pulled from
+/// `G_CONFIG`. Falls back to the raw embedded asset if `index.html` never
+/// opted in to templating, or failed to register as one (see the module
+/// docs).
+pub fn render_index() -> crate::Result<String> {
+    let hb = REGISTRY.get().unwrap();
+    if !hb.has_template(INDEX_TEMPLATE) {
+        let index_html = crate::Asset::get("/index.html").ok_or("missing embedded index.html")?;
+        return Ok(String::from_utf8(index_html.data.into_owned())?);
+    }
+
+    let cfg = crate::G_CONFIG.get().unwrap().read().unwrap();
+    let ctx = json!({
+        "title": cfg.site_title(),
+        "theme": cfg.theme(),
+        "refresh_interval": cfg.refresh_interval(),
+        "columns": cfg.dashboard_columns(),
+        "groups": cfg.group_display_names(),
+    });
+    Ok(hb.render(INDEX_TEMPLATE, &ctx)?)
+}
@@ -0,0 +1,141 @@
+//! Optional native HTTPS listener via rustls.
+//!
+//! `main` used to only ever bind plain HTTP. When `tls_cert`/`tls_key` are
+//! configured it instead serves the same `main_service_func` over HTTPS,
+//! with graceful shutdown handled the same way as the plain-HTTP listener;
+//! a plain-HTTP listener on `http_addr`, if set, only redirects to it.
+
+use hyper::service::service_fn;
+use hyper::{Body, Response, StatusCode};
+use rustls_pemfile::{certs, Item};
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// Builds a `TlsAcceptor` from a PEM-encoded certificate chain and private
+/// key on disk.
+pub fn load_acceptor(cert_path: &str, key_path: &str) -> crate::Result<TlsAcceptor> {
+    let cert_chain = certs(&mut BufReader::new(File::open(cert_path)?))?
+        .into_iter()
+        .map(Certificate)
+        .collect::<Vec<_>>();
+    let key = load_private_key(key_path)?;
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Reads the first private key out of `key_path`, accepting PKCS#8
+/// (`BEGIN PRIVATE KEY`), PKCS#1 RSA (`BEGIN RSA PRIVATE KEY`), and SEC1 EC
+/// (`BEGIN EC PRIVATE KEY`) PEM sections.
+fn load_private_key(key_path: &str) -> crate::Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(key_path)?);
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(Item::PKCS8Key(key)) | Some(Item::RSAKey(key)) | Some(Item::ECKey(key)) => {
+                return Ok(PrivateKey(key))
+            }
+            Some(_) => continue,
+            None => {
+                return Err(
+                    format!("no PKCS#8/RSA/EC private key found in {}", key_path).into(),
+                )
+            }
+        }
+    }
+}
+
+/// Accepts connections on `addr`, TLS-handshakes each one, then serves it
+/// with `main_service_func` just like the plain-HTTP listener. Runs until
+/// `shutdown` resolves.
+pub async fn serve_https(
+    addr: SocketAddr,
+    acceptor: TlsAcceptor,
+    stats_mgr: Arc<crate::stats::StatsMgr>,
+    shutdown: impl Future<Output = ()>,
+) -> crate::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("Listening on https://{}", addr);
+
+    // Tracks the spawned per-connection tasks so shutdown can wait for them
+    // to finish, the same as `with_graceful_shutdown` does for plain HTTP.
+    let mut conns = tokio::task::JoinSet::new();
+
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => {
+                let (stream, _peer_addr) = accepted?;
+                let acceptor = acceptor.clone();
+                let stats_mgr = stats_mgr.clone();
+                conns.spawn(async move {
+                    let tls_stream = match acceptor.accept(stream).await {
+                        Ok(tls_stream) => tls_stream,
+                        Err(e) => {
+                            error!("tls handshake failed: {}", e);
+                            return;
+                        }
+                    };
+                    let service = service_fn(move |req| crate::main_service_func(req, stats_mgr.clone()));
+                    if let Err(e) = hyper::server::conn::Http::new()
+                        .serve_connection(tls_stream, service)
+                        .with_upgrades()
+                        .await
+                    {
+                        error!("https connection error: {}", e);
+                    }
+                });
+            }
+        }
+    }
+
+    // Stop accepting new connections, but let in-flight ones finish.
+    while conns.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// Plain-HTTP listener that just redirects every request to the HTTPS
+/// listener on `https_port`, for when both are configured.
+pub async fn serve_http_redirect(
+    addr: SocketAddr,
+    https_port: u16,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> crate::Result<()> {
+    let make_svc = hyper::service::make_service_fn(move |_| async move {
+        Ok::<_, crate::GenericError>(service_fn(move |req: hyper::Request<Body>| async move {
+            let host = req
+                .headers()
+                .get(hyper::header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or("localhost")
+                .split(':')
+                .next()
+                .unwrap_or("localhost");
+            let location = format!("https://{}:{}{}", host, https_port, req.uri());
+            Ok::<_, crate::GenericError>(
+                Response::builder()
+                    .status(StatusCode::MOVED_PERMANENTLY)
+                    .header(hyper::header::LOCATION, location)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        }))
+    });
+
+    println!("Redirecting http://{} to https (port {})", addr, https_port);
+    hyper::Server::bind(&addr)
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! WebSocket push transport for `/ws`: forwards every stats refresh to
+//! subscribed browsers instead of making them poll `/json/stats.json`.
+
+use crate::auth::ApiAuth;
+use crate::{stats, Result};
+use futures_util::{SinkExt, StreamExt};
+use hyper::upgrade::Upgraded;
+use hyper::{Body, Request, Response, StatusCode};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{Message, Role};
+use tokio_tungstenite::WebSocketStream;
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A browser's `new WebSocket()` can't set an `Authorization` header, so a
+/// bearer ticket is also accepted via `?ticket=...`; anything else (curl,
+/// wscat, etc.) can still use the header the way `AnyAuth` expects.
+fn is_authenticated(req: &Request<Body>) -> bool {
+    if crate::auth::AnyAuth.check_auth(req.headers()).is_some() {
+        return true;
+    }
+    ticket_query_param(req.uri().query().unwrap_or(""))
+        .and_then(crate::auth::check_ticket)
+        .is_some()
+}
+
+fn ticket_query_param(query: &str) -> Option<&str> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == "ticket").then_some(value)
+    })
+}
+
+/// Upgrades `/ws` GET requests to a WebSocket connection and spawns a task
+/// that streams stats updates to the client until it disconnects.
+pub async fn handle_upgrade(
+    mut req: Request<Body>,
+    stats_mgr: Arc<stats::StatsMgr>,
+) -> Result<Response<Body>> {
+    if !is_authenticated(&req) {
+        return Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(crate::UNAUTHORIZED.into())
+            .unwrap());
+    }
+
+    let key = match req.headers().get(hyper::header::SEC_WEBSOCKET_KEY) {
+        Some(key) => key.clone(),
+        None => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from("missing Sec-WebSocket-Key"))
+                .unwrap())
+        }
+    };
+    let accept_key = derive_accept_key(key.as_bytes());
+
+    tokio::spawn(async move {
+        match hyper::upgrade::on(&mut req).await {
+            Ok(upgraded) => {
+                let ws_stream = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                if let Err(e) = serve(ws_stream, stats_mgr).await {
+                    error!("ws session error: {}", e);
+                }
+            }
+            Err(e) => error!("ws upgrade failed: {}", e),
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(hyper::header::CONNECTION, "upgrade")
+        .header(hyper::header::UPGRADE, "websocket")
+        .header(hyper::header::SEC_WEBSOCKET_ACCEPT, accept_key)
+        .body(Body::empty())
+        .unwrap())
+}
+
+async fn serve(ws_stream: WebSocketStream<Upgraded>, stats_mgr: Arc<stats::StatsMgr>) -> Result<()> {
+    let (mut sink, mut stream) = ws_stream.split();
+    let mut updates = stats_mgr.subscribe();
+
+    // Give the new client the current snapshot immediately instead of
+    // making it wait for the next refresh.
+    sink.send(Message::Text(stats_mgr.get_stats_json())).await?;
+
+    let mut ping_tick = tokio::time::interval(PING_INTERVAL);
+    loop {
+        tokio::select! {
+            biased;
+            _ = tokio::signal::ctrl_c() => {
+                let _ = sink.send(Message::Close(None)).await;
+                break;
+            }
+            msg = stream.next() => {
+                match msg {
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => {} // client -> server frames carry nothing we act on
+                    Some(Err(e)) => {
+                        warn!("ws recv error: {}", e);
+                        break;
+                    }
+                }
+            }
+            _ = ping_tick.tick() => {
+                if sink.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+            update = updates.recv() => {
+                match update {
+                    Ok(payload) => {
+                        if sink.send(Message::Text(payload)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        // Drop the backlog rather than buffering unboundedly;
+                        // the skipped snapshots are lost and the client resumes
+                        // from the oldest one still held in the channel.
+                        warn!("ws client lagged, dropped {} update(s)", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+    Ok(())
+}